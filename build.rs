@@ -42,7 +42,7 @@ fn main() {
 
     flatc_rust::run(flatc_rust::Args {
         lang: "rust",
-        inputs: &fbs_files.iter().map(|s| Path::new(s)).collect::<Vec<_>>(),
+        inputs: &fbs_files.iter().map(Path::new).collect::<Vec<_>>(),
         out_dir: Path::new(&out_dir),
         includes: &[Path::new("src")],
         ..Default::default()
@@ -0,0 +1,104 @@
+use crate::core::SortOrder;
+use crate::generator::GeneratorOptions;
+
+#[derive(Default)]
+pub struct Options {
+    pub blocklist: Vec<String>,
+    pub trace: Vec<String>,
+    pub warn_output_bytes: Option<u64>,
+    pub classmap: bool,
+    pub sort_order: SortOrder,
+    pub generator: GeneratorOptions,
+}
+
+pub fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--blocklist" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{} expects a comma-separated list", arg))?;
+                options.blocklist.extend(
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|pattern| !pattern.is_empty())
+                        .map(str::to_owned),
+                );
+            }
+            "--trace" => {
+                let class = args
+                    .next()
+                    .ok_or_else(|| format!("{} expects a class name", arg))?;
+                options.trace.push(class);
+            }
+            "--warn-output-bytes" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{} expects a byte count", arg))?;
+                let bytes = value
+                    .parse()
+                    .map_err(|_| format!("Invalid byte count for {}: {}", arg, value))?;
+                options.warn_output_bytes = Some(bytes);
+            }
+            "--default-declaration" => {
+                options.generator.default_declaration = args
+                    .next()
+                    .ok_or_else(|| format!("{} expects a CSS declaration block", arg))?;
+            }
+            "--emit-empty-rules" => options.generator.skip_empty = false,
+            "--rule-separator" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{} expects double, single or none", arg))?;
+                options.generator.rule_separator = value.parse()?;
+            }
+            "--classmap" => options.classmap = true,
+            "--sort" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{} expects alphabetical or source", arg))?;
+                options.sort_order = value.parse()?;
+            }
+            "--banner" => {
+                let banner = args
+                    .next()
+                    .ok_or_else(|| format!("{} expects the banner text", arg))?;
+                options.generator.banner = Some(banner);
+            }
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn blocklist_splits_and_trims_patterns() {
+        let options =
+            parse(&["--blocklist", "active, carousel-* ,", "--blocklist", "*-x"]).unwrap();
+        assert_eq!(options.blocklist, ["active", "carousel-*", "*-x"]);
+    }
+
+    #[test]
+    fn flag_without_value_is_an_error() {
+        let error = parse(&["--blocklist"]).err().unwrap();
+        assert_eq!(error, "--blocklist expects a comma-separated list");
+    }
+
+    #[test]
+    fn unknown_argument_is_an_error() {
+        let error = parse(&["--bogus"]).err().unwrap();
+        assert_eq!(error, "Unknown argument: --bogus");
+    }
+}
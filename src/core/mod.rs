@@ -1,26 +1,203 @@
-use crate::{generator, parser::extract_classes_fast, telemetry::format_duration};
-use ahash::{AHashSet, AHasher};
+use crate::{
+    generator::{self, GeneratorOptions},
+    parser::{extract_classes_fast, extract_classes_first_seen},
+    telemetry::format_duration,
+};
+use ahash::{AHashMap, AHashSet, AHasher};
 use colored::Colorize;
 use std::fs::File;
 use std::hash::Hasher;
-use std::io::BufWriter;
+use std::io::{BufWriter, Write};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
 pub struct AppState {
     pub html_hash: u64,
     pub class_cache: AHashSet<String>,
+    pub unresolved_classes: AHashSet<String>,
     pub css_file: BufWriter<File>,
+    pub blocklist: Vec<String>,
+    pub traced_classes: AHashSet<String>,
+    pub warn_output_bytes: Option<u64>,
+    pub classmap: bool,
+    pub sort_order: SortOrder,
+    pub generator_options: GeneratorOptions,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    #[default]
+    Alphabetical,
+    Source,
+}
+
+impl std::str::FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "alphabetical" => Ok(SortOrder::Alphabetical),
+            "source" => Ok(SortOrder::Source),
+            other => Err(format!(
+                "Unknown sort order: {} (expected alphabetical or source)",
+                other
+            )),
+        }
+    }
+}
+
+const HTML_PATH: &str = "playgrounds/html/index.html";
+const CLASSMAP_PATH: &str = ".dx/classmap.json";
+
+fn is_blocklisted(class: &str, blocklist: &[String]) -> bool {
+    blocklist.iter().any(|pattern| glob_match(pattern, class))
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = text.strip_prefix(parts.next().unwrap_or("")) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+fn sort_classes(classes: &mut [String], first_seen: Option<&AHashMap<String, usize>>) {
+    match first_seen {
+        None => classes.sort_unstable(),
+        Some(first_seen) => {
+            classes.sort_by_key(|class| first_seen.get(class).copied().unwrap_or(usize::MAX))
+        }
+    }
+}
+
+// Appending keeps the file sorted only if every new rule sorts after the
+// last rule already written; otherwise the whole file is regenerated.
+fn appends_out_of_order(state: &AppState, added: &[String]) -> bool {
+    let written = |class: &&String| !is_blocklisted(class, &state.blocklist);
+    let last_written = state.class_cache.iter().filter(written).max();
+    let first_added = added.iter().filter(written).min();
+    matches!((first_added, last_written), (Some(first), Some(last)) if first < last)
+}
+
+fn classmap_classes(state: &AppState) -> Vec<String> {
+    let mut classes: Vec<String> = state
+        .class_cache
+        .iter()
+        .filter(|class| {
+            !is_blocklisted(class, &state.blocklist) && !state.unresolved_classes.contains(*class)
+        })
+        .cloned()
+        .collect();
+    classes.sort_unstable();
+    classes
+}
+
+fn trace_classes<W: Write>(
+    out: &mut W,
+    state: &AppState,
+    added: &[String],
+    removed: &[String],
+    full_rewrite: bool,
+    html_path: &Path,
+) -> std::io::Result<()> {
+    let mode = if full_rewrite {
+        "full rewrite"
+    } else {
+        "append"
+    };
+    let mut escaped = String::with_capacity(64);
+    let mut traced: Vec<&String> = state.traced_classes.iter().collect();
+    traced.sort_unstable();
+
+    for class in traced {
+        let event = if added.contains(class) {
+            "added"
+        } else if removed.contains(class) {
+            "removed"
+        } else if full_rewrite && state.class_cache.contains(class) {
+            "regenerated"
+        } else {
+            continue;
+        };
+
+        let css = if event == "removed" {
+            "(none)".to_string()
+        } else if is_blocklisted(class, &state.blocklist) {
+            "(blocklisted)".to_string()
+        } else {
+            let mut rule = Vec::new();
+            match generator::write_rule(&mut rule, class, &mut escaped, &state.generator_options) {
+                Ok(_) if rule.is_empty() => "(skipped, empty rule)".to_string(),
+                Ok(_) => String::from_utf8_lossy(&rule)
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                Err(e) => format!("(error: {})", e),
+            }
+        };
+
+        writeln!(
+            out,
+            "{} {} {} via {} ({}): {}",
+            "Trace".yellow(),
+            class.bold(),
+            event,
+            html_path.display(),
+            mode,
+            css
+        )?;
+    }
+    Ok(())
+}
+
+fn warn_output_size<W: Write>(out: &mut W, state: &AppState) -> std::io::Result<()> {
+    if let Some(limit) = state.warn_output_bytes {
+        let output_bytes = state.css_file.get_ref().metadata()?.len();
+        if output_bytes > limit {
+            writeln!(
+                out,
+                "{} output is {} bytes (limit {}) for {} classes",
+                "Warning:".yellow(),
+                output_bytes,
+                limit,
+                state.class_cache.len()
+            )?;
+        }
+    }
+    Ok(())
 }
 
 pub fn rebuild_styles(
     state: Arc<Mutex<AppState>>,
     is_initial_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    rebuild_styles_from(state, is_initial_run, Path::new(HTML_PATH))
+}
+
+fn rebuild_styles_from(
+    state: Arc<Mutex<AppState>>,
+    is_initial_run: bool,
+    html_path: &Path,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let total_start = Instant::now();
 
+    // Held from before the read through the write so concurrent rebuilds run
+    // one at a time, and the last one to run always sees the latest HTML.
+    let mut state_guard = state.lock().unwrap();
+
     let read_timer = Instant::now();
-    let html_bytes = std::fs::read("playgrounds/html/index.html")?;
+    let html_bytes = std::fs::read(html_path)?;
     let read_duration = read_timer.elapsed();
 
     let hash_timer = Instant::now();
@@ -31,60 +208,120 @@ pub fn rebuild_styles(
     };
     let hash_duration = hash_timer.elapsed();
 
-    {
-        let state_guard = state.lock().unwrap();
-        if !is_initial_run && state_guard.html_hash == new_html_hash {
-            return Ok(());
-        }
+    if !is_initial_run && state_guard.html_hash == new_html_hash {
+        return Ok(());
     }
 
     let parse_timer = Instant::now();
-    let prev_len_hint = { state.lock().unwrap().class_cache.len() };
-    let all_classes = extract_classes_fast(&html_bytes, prev_len_hint.next_power_of_two());
+    let prev_len_hint = state_guard.class_cache.len();
+    let capacity_hint = prev_len_hint.next_power_of_two();
+    let first_seen = (state_guard.sort_order == SortOrder::Source)
+        .then(|| extract_classes_first_seen(&html_bytes, capacity_hint));
+    let all_classes = match &first_seen {
+        Some(first_seen) => first_seen.keys().cloned().collect(),
+        None => extract_classes_fast(&html_bytes, capacity_hint),
+    };
     let parse_extract_duration = parse_timer.elapsed();
 
-    {
-        let state_guard = state.lock().unwrap();
-        if all_classes.is_empty() && !state_guard.class_cache.is_empty() {
-            return Ok(());
-        }
+    if all_classes.is_empty() && !state_guard.class_cache.is_empty() {
+        return Ok(());
     }
 
     let diff_timer = Instant::now();
-    let (added, removed, old_hash_just_for_info) = {
-        let state_guard = state.lock().unwrap();
-        let old = &state_guard.class_cache;
-        let added: Vec<String> = all_classes.difference(old).cloned().collect();
-        let removed: Vec<String> = old.difference(&all_classes).cloned().collect();
-        (added, removed, state_guard.html_hash)
-    };
+    let added: Vec<String> = all_classes
+        .difference(&state_guard.class_cache)
+        .cloned()
+        .collect();
+    let removed: Vec<String> = state_guard
+        .class_cache
+        .difference(&all_classes)
+        .cloned()
+        .collect();
+    let old_hash_just_for_info = state_guard.html_hash;
+    // First-seen positions shift with any edit, so source order is only kept
+    // by regenerating the whole file once something has been written.
+    let full_rewrite = !removed.is_empty()
+        || match first_seen {
+            Some(_) => !state_guard.class_cache.is_empty(),
+            None => appends_out_of_order(&state_guard, &added),
+        };
     let diff_duration = diff_timer.elapsed();
 
-    if added.is_empty() && removed.is_empty() {
-        let mut state_guard = state.lock().unwrap();
+    if added.is_empty() && !full_rewrite {
         state_guard.html_hash = new_html_hash;
         return Ok(());
     }
 
     let cache_update_timer = Instant::now();
-    {
-        let mut state_guard = state.lock().unwrap();
-        state_guard.html_hash = new_html_hash;
-        state_guard.class_cache = all_classes.clone();
-    }
+    state_guard.html_hash = new_html_hash;
+    state_guard.class_cache = all_classes;
     let cache_update_duration = cache_update_timer.elapsed();
 
     let css_write_timer = Instant::now();
-    {
-        let mut state_guard = state.lock().unwrap();
+    let unresolved = if full_rewrite {
+        let mut classes_to_write: Vec<String> = state_guard
+            .class_cache
+            .iter()
+            .filter(|class| !is_blocklisted(class, &state_guard.blocklist))
+            .cloned()
+            .collect();
+        sort_classes(&mut classes_to_write, first_seen.as_ref());
+        let state = &mut *state_guard;
+        generator::write_css(
+            &mut state.css_file,
+            classes_to_write,
+            false,
+            &state.generator_options,
+        )?
+    } else {
+        let mut classes_to_write: Vec<String> = added
+            .iter()
+            .filter(|class| !is_blocklisted(class, &state_guard.blocklist))
+            .cloned()
+            .collect();
+        sort_classes(&mut classes_to_write, first_seen.as_ref());
+        let state = &mut *state_guard;
+        generator::write_css(
+            &mut state.css_file,
+            classes_to_write,
+            true,
+            &state.generator_options,
+        )?
+    };
 
-        if !removed.is_empty() {
-            let classes_to_write: Vec<String> = state_guard.class_cache.iter().cloned().collect();
-            generator::write_css(&mut state_guard.css_file, classes_to_write, false)?;
-        } else {
-            generator::write_css(&mut state_guard.css_file, added.clone(), true)?;
-        }
+    if !unresolved.is_empty() {
+        println!(
+            "{} {} unresolved classes: {}",
+            "Warning:".yellow(),
+            unresolved.len(),
+            unresolved.join(", ")
+        );
     }
+
+    if full_rewrite {
+        state_guard.unresolved_classes.clear();
+    }
+    state_guard
+        .unresolved_classes
+        .extend(unresolved.iter().cloned());
+
+    if state_guard.classmap {
+        generator::write_classmap(Path::new(CLASSMAP_PATH), &classmap_classes(&state_guard))?;
+    }
+
+    if !state_guard.traced_classes.is_empty() {
+        trace_classes(
+            &mut std::io::stdout().lock(),
+            &state_guard,
+            &added,
+            &removed,
+            full_rewrite,
+            html_path,
+        )?;
+    }
+
+    warn_output_size(&mut std::io::stdout().lock(), &state_guard)?;
+    drop(state_guard);
     let css_write_duration = css_write_timer.elapsed();
 
     println!(
@@ -103,3 +340,303 @@ pub fn rebuild_styles(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, OpenOptions};
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dx-style-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn test_state(dir: &Path) -> AppState {
+        let css_file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(dir.join("style.css"))
+            .unwrap();
+        AppState {
+            html_hash: 0,
+            class_cache: AHashSet::default(),
+            unresolved_classes: AHashSet::default(),
+            css_file: BufWriter::new(css_file),
+            blocklist: Vec::new(),
+            traced_classes: AHashSet::default(),
+            warn_output_bytes: None,
+            classmap: false,
+            sort_order: SortOrder::default(),
+            generator_options: GeneratorOptions::default(),
+        }
+    }
+
+    fn rebuild(state: &Arc<Mutex<AppState>>, dir: &Path, html: &str) -> String {
+        let html_path = dir.join("index.html");
+        fs::write(&html_path, html).unwrap();
+        rebuild_styles_from(state.clone(), false, &html_path).unwrap();
+        fs::read_to_string(dir.join("style.css")).unwrap()
+    }
+
+    #[test]
+    fn glob_match_patterns() {
+        assert!(glob_match("active", "active"));
+        assert!(!glob_match("active", "inactive"));
+        assert!(glob_match("carousel-*", "carousel-item"));
+        assert!(glob_match("carousel-*", "carousel-"));
+        assert!(!glob_match("carousel-*", "my-carousel-item"));
+        assert!(glob_match("*slide*", "js-slide-next"));
+        assert!(glob_match("*-x*-y", "a-x-b-y"));
+        assert!(!glob_match("*slide*", "slid"));
+        assert!(!glob_match("bg-*-500", "bg-red-400"));
+    }
+
+    #[test]
+    fn blocklisted_class_produces_no_rule() {
+        let dir = temp_dir("blocklist");
+        let mut state = test_state(&dir);
+        state.blocklist = vec!["active".to_string(), "carousel-*".to_string()];
+        let state = Arc::new(Mutex::new(state));
+
+        let css = rebuild(
+            &state,
+            &dir,
+            r#"<div class="active actives carousel-item"></div>"#,
+        );
+        assert!(css.contains(".actives {"));
+        assert!(!css.contains(".active {"));
+        assert!(!css.contains("carousel-item"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn trace_reports_only_traced_classes() {
+        let dir = temp_dir("trace");
+        let mut state = test_state(&dir);
+        state.traced_classes = ["bg-red".to_string(), "hidden".to_string()]
+            .into_iter()
+            .collect();
+        state.class_cache = ["bg-red".to_string(), "p-4".to_string()]
+            .into_iter()
+            .collect();
+        let added = vec!["bg-red".to_string(), "p-4".to_string()];
+
+        let mut out = Vec::new();
+        trace_classes(
+            &mut out,
+            &state,
+            &added,
+            &[],
+            false,
+            Path::new("index.html"),
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("bg-red"));
+        assert!(lines[0].contains("added via index.html (append): .bg-red { display: flex; }"));
+        assert!(!out.contains("p-4"));
+        assert!(!out.contains("hidden"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn output_is_independent_of_edit_history() {
+        let edited = temp_dir("history-edited");
+        let state = Arc::new(Mutex::new(test_state(&edited)));
+        rebuild(&state, &edited, r#"<div class="a c"></div>"#);
+        rebuild(&state, &edited, r#"<div class="a c z"></div>"#);
+        let edited_css = rebuild(&state, &edited, r#"<div class="z c a b"></div>"#);
+
+        let fresh = temp_dir("history-fresh");
+        let state = Arc::new(Mutex::new(test_state(&fresh)));
+        let fresh_css = rebuild(&state, &fresh, r#"<div class="z c a b"></div>"#);
+
+        assert_eq!(edited_css, fresh_css);
+        assert_eq!(
+            fresh_css,
+            ".a {\n  display: flex;\n}\n.b {\n  display: flex;\n}\n.c {\n  display: flex;\n}\n.z {\n  display: flex;\n}\n"
+        );
+
+        fs::remove_dir_all(&edited).unwrap();
+        fs::remove_dir_all(&fresh).unwrap();
+    }
+
+    #[test]
+    fn output_size_warning_respects_threshold() {
+        let dir = temp_dir("warn-bytes");
+        let state = Arc::new(Mutex::new(test_state(&dir)));
+        rebuild(&state, &dir, r#"<div class="p-4 m-1"></div>"#);
+        let mut state = state.lock().unwrap();
+
+        state.warn_output_bytes = Some(8);
+        let mut out = Vec::new();
+        warn_output_size(&mut out, &state).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("(limit 8) for 2 classes"));
+
+        state.warn_output_bytes = Some(1 << 20);
+        let mut out = Vec::new();
+        warn_output_size(&mut out, &state).unwrap();
+        assert!(out.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn concurrent_rebuilds_leave_output_matching_latest_html() {
+        let dir = temp_dir("concurrent");
+        let html_path = dir.join("index.html");
+        fs::write(&html_path, "").unwrap();
+        let state = Arc::new(Mutex::new(test_state(&dir)));
+
+        let workers: Vec<_> = (0..2)
+            .map(|worker| {
+                let state = state.clone();
+                let dir = dir.clone();
+                let html_path = html_path.clone();
+                std::thread::spawn(move || {
+                    for round in 0..100 {
+                        let staging = dir.join(format!("index-{}.html", worker));
+                        let html = format!(r#"<div class="w{}-r{} shared"></div>"#, worker, round);
+                        fs::write(&staging, html).unwrap();
+                        fs::rename(&staging, &html_path).unwrap();
+                        rebuild_styles_from(state.clone(), false, &html_path).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        let html = fs::read(&html_path).unwrap();
+        let mut classes: Vec<String> = extract_classes_fast(&html, 4).into_iter().collect();
+        classes.sort_unstable();
+        let expected: String = classes
+            .iter()
+            .map(|class| format!(".{} {{\n  display: flex;\n}}\n", class))
+            .collect();
+        assert_eq!(fs::read_to_string(dir.join("style.css")).unwrap(), expected);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn queued_rebuild_reads_html_after_taking_the_lock() {
+        let dir = temp_dir("queued");
+        let state = Arc::new(Mutex::new(test_state(&dir)));
+        rebuild(&state, &dir, r#"<div class="v1"></div>"#);
+
+        let html_path = dir.join("index.html");
+        fs::write(&html_path, r#"<div class="v2"></div>"#).unwrap();
+        let guard = state.lock().unwrap();
+        let queued = {
+            let state = state.clone();
+            let html_path = html_path.clone();
+            std::thread::spawn(move || rebuild_styles_from(state, false, &html_path).unwrap())
+        };
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        fs::write(&html_path, r#"<div class="v3"></div>"#).unwrap();
+        drop(guard);
+        queued.join().unwrap();
+
+        let css = fs::read_to_string(dir.join("style.css")).unwrap();
+        assert_eq!(css, ".v3 {\n  display: flex;\n}\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn classmap_skips_blocklisted_and_unresolved_classes() {
+        let dir = temp_dir("classmap");
+        let mut state = test_state(&dir);
+        state.blocklist = vec!["active".to_string()];
+        state.generator_options.resolver = Some(Box::new(|class: &str| {
+            if class.starts_with("flexx") {
+                String::new()
+            } else {
+                "display: flex;".to_string()
+            }
+        }));
+        let state = Arc::new(Mutex::new(state));
+
+        rebuild(&state, &dir, r#"<div class="p-4 flexx active"></div>"#);
+        assert_eq!(classmap_classes(&state.lock().unwrap()), ["p-4"]);
+
+        rebuild(
+            &state,
+            &dir,
+            r#"<div class="p-4 flexx active m-1 flexx-2"></div>"#,
+        );
+        assert_eq!(classmap_classes(&state.lock().unwrap()), ["m-1", "p-4"]);
+
+        rebuild(&state, &dir, r#"<div class="m-1 flexx-2"></div>"#);
+        assert_eq!(classmap_classes(&state.lock().unwrap()), ["m-1"]);
+        assert_eq!(
+            state.lock().unwrap().unresolved_classes,
+            ["flexx-2".to_string()].into_iter().collect()
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn source_sort_follows_first_appearance() {
+        let dir = temp_dir("source-order");
+        let mut state = test_state(&dir);
+        state.sort_order = SortOrder::Source;
+        state.generator_options.rule_separator = generator::RuleSeparator::None;
+        let state = Arc::new(Mutex::new(state));
+
+        let css = rebuild(
+            &state,
+            &dir,
+            r#"<div class="z-1 b-2 a-3"><p class="m-1 b-2"></p></div>"#,
+        );
+        let selectors: Vec<&str> = css
+            .lines()
+            .map(|line| line.split(' ').next().unwrap())
+            .collect();
+        assert_eq!(selectors, [".z-1", ".b-2", ".a-3", ".m-1"]);
+
+        let css = rebuild(
+            &state,
+            &dir,
+            r#"<div class="z-1 y-9 b-2 a-3"><p class="m-1 b-2 c-0"></p></div>"#,
+        );
+        let selectors: Vec<&str> = css
+            .lines()
+            .map(|line| line.split(' ').next().unwrap())
+            .collect();
+        assert_eq!(selectors, [".z-1", ".y-9", ".b-2", ".a-3", ".m-1", ".c-0"]);
+
+        let css = rebuild(
+            &state,
+            &dir,
+            r#"<div class="c-0 m-1 a-3 b-2 y-9 z-1"></div>"#,
+        );
+        let selectors: Vec<&str> = css
+            .lines()
+            .map(|line| line.split(' ').next().unwrap())
+            .collect();
+        assert_eq!(selectors, [".c-0", ".m-1", ".a-3", ".b-2", ".y-9", ".z-1"]);
+
+        let css = rebuild(&state, &dir, r#"<div class="c-0 b-2 z-1 y-9"></div>"#);
+        let selectors: Vec<&str> = css
+            .lines()
+            .map(|line| line.split(' ').next().unwrap())
+            .collect();
+        assert_eq!(selectors, [".c-0", ".b-2", ".z-1", ".y-9"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
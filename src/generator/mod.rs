@@ -1,27 +1,427 @@
-use cssparser::serialize_identifier;
+use cssparser::{Delimiter, ParseError, Parser, ParserInput, serialize_identifier};
+use std::borrow::Cow;
 use std::fs::File;
 use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RuleSeparator {
+    Double,
+    Single,
+    None,
+}
+
+impl RuleSeparator {
+    fn open_rule(self) -> &'static [u8] {
+        match self {
+            RuleSeparator::None => b" {",
+            _ => b" {\n",
+        }
+    }
+
+    fn declaration(self) -> (&'static [u8], &'static [u8]) {
+        match self {
+            RuleSeparator::None => (b" ", b";"),
+            _ => (b"  ", b";\n"),
+        }
+    }
+
+    fn close_rule(self) -> &'static [u8] {
+        match self {
+            RuleSeparator::Double => b"}\n\n",
+            RuleSeparator::Single => b"}\n",
+            RuleSeparator::None => b" }\n",
+        }
+    }
+}
+
+impl std::str::FromStr for RuleSeparator {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "double" => Ok(RuleSeparator::Double),
+            "single" => Ok(RuleSeparator::Single),
+            "none" => Ok(RuleSeparator::None),
+            other => Err(format!(
+                "Unknown rule separator: {} (expected double, single or none)",
+                other
+            )),
+        }
+    }
+}
+
+pub trait RuleResolver {
+    fn declarations(&self, class: &str) -> Cow<'_, str>;
+}
+
+impl<F> RuleResolver for F
+where
+    F: Fn(&str) -> String,
+{
+    fn declarations(&self, class: &str) -> Cow<'_, str> {
+        Cow::Owned(self(class))
+    }
+}
+
+pub struct GeneratorOptions {
+    pub default_declaration: String,
+    pub resolver: Option<Box<dyn RuleResolver + Send>>,
+    pub skip_empty: bool,
+    pub rule_separator: RuleSeparator,
+    pub banner: Option<String>,
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        Self {
+            default_declaration: "display: flex;".to_string(),
+            resolver: None,
+            skip_empty: true,
+            rule_separator: RuleSeparator::Single,
+            banner: None,
+        }
+    }
+}
 
 pub fn write_css(
     css_file: &mut BufWriter<File>,
     classes_to_write: Vec<String>,
     append: bool,
-) -> Result<(), std::io::Error> {
-    if !append {
+    options: &GeneratorOptions,
+) -> Result<Vec<String>, std::io::Error> {
+    let position = if !append {
         css_file.get_mut().set_len(0)?;
-        css_file.seek(SeekFrom::Start(0))?;
+        css_file.seek(SeekFrom::Start(0))?
     } else {
-        css_file.seek(SeekFrom::End(0))?;
+        css_file.seek(SeekFrom::End(0))?
+    };
+
+    if let Some(banner) = &options.banner
+        && position == 0
+    {
+        writeln!(css_file, "{}", banner_comment(banner))?;
     }
 
     let mut escaped = String::with_capacity(64);
+    let mut unresolved = Vec::new();
     for class in classes_to_write {
-        css_file.write_all(b".")?;
-        escaped.clear();
-        serialize_identifier(&class, &mut escaped).unwrap();
-        css_file.write_all(escaped.as_bytes())?;
-        css_file.write_all(b" {\n  display: flex;\n}\n")?;
+        if !write_rule(css_file, &class, &mut escaped, options)? {
+            unresolved.push(class);
+        }
     }
     css_file.flush()?;
-    Ok(())
+    Ok(unresolved)
+}
+
+// Always a single well-formed comment: an existing /* ... */ wrapper is
+// kept, plain text gets one, and any inner */ is broken up.
+fn banner_comment(banner: &str) -> String {
+    let trimmed = banner.trim();
+    let body = match trimmed
+        .strip_prefix("/*")
+        .and_then(|rest| rest.strip_suffix("*/"))
+    {
+        Some(body) => Cow::Borrowed(body),
+        None => Cow::Owned(format!(" {} ", trimmed)),
+    };
+    format!("/*{}*/", body.replace("*/", "* /"))
+}
+
+pub fn write_rule<W: Write>(
+    out: &mut W,
+    class: &str,
+    escaped: &mut String,
+    options: &GeneratorOptions,
+) -> Result<bool, std::io::Error> {
+    let block = match &options.resolver {
+        Some(resolver) => resolver.declarations(class),
+        None => Cow::Borrowed(options.default_declaration.as_str()),
+    };
+    let declarations = split_declarations(&block);
+    let resolved = !declarations.is_empty();
+    if options.skip_empty && !resolved {
+        return Ok(false);
+    }
+
+    out.write_all(b".")?;
+    escaped.clear();
+    serialize_identifier(class, escaped).unwrap();
+    out.write_all(escaped.as_bytes())?;
+    let separator = options.rule_separator;
+    let (indent, terminator) = separator.declaration();
+    out.write_all(separator.open_rule())?;
+    for declaration in declarations {
+        out.write_all(indent)?;
+        out.write_all(declaration.as_bytes())?;
+        out.write_all(terminator)?;
+    }
+    out.write_all(separator.close_rule())?;
+    Ok(resolved)
+}
+
+fn split_declarations(block: &str) -> Vec<&str> {
+    let mut input = ParserInput::new(block);
+    let mut parser = Parser::new(&mut input);
+    let mut declarations = Vec::new();
+    while !parser.is_exhausted() {
+        let start = parser.position();
+        let _ = parser.parse_until_before(Delimiter::Semicolon, |p| {
+            while p.next().is_ok() {}
+            Ok::<_, ParseError<()>>(())
+        });
+        let declaration = parser.slice_from(start).trim();
+        if !declaration.is_empty() {
+            declarations.push(declaration);
+        }
+        let _ = parser.next();
+    }
+    declarations
+}
+
+pub fn write_classmap(path: &Path, classes: &[String]) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(b"{")?;
+    for (i, class) in classes.iter().enumerate() {
+        out.write_all(if i == 0 { b"\n  " } else { b",\n  " })?;
+        write_json_string(&mut out, class)?;
+        out.write_all(b": ")?;
+        write_json_string(&mut out, class)?;
+    }
+    out.write_all(if classes.is_empty() { b"}\n" } else { b"\n}\n" })?;
+    out.flush()
+}
+
+fn write_json_string<W: Write>(out: &mut W, value: &str) -> Result<(), std::io::Error> {
+    out.write_all(b"\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => out.write_all(b"\\\"")?,
+            '\\' => out.write_all(b"\\\\")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    out.write_all(b"\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(class: &str, options: &GeneratorOptions) -> (bool, String) {
+        let mut out = Vec::new();
+        let resolved = write_rule(&mut out, class, &mut String::new(), options).unwrap();
+        (resolved, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn split_keeps_semicolons_inside_urls_and_strings() {
+        assert_eq!(
+            split_declarations("background: url(data:image/png;base64,AAA=); content: \";\";;"),
+            [
+                "background: url(data:image/png;base64,AAA=)",
+                "content: \";\""
+            ]
+        );
+        assert_eq!(
+            split_declarations("background-image: url(\"a;b.png\"); grid-area: 1 / 2"),
+            ["background-image: url(\"a;b.png\")", "grid-area: 1 / 2"]
+        );
+    }
+
+    #[test]
+    fn configured_default_declaration_is_written() {
+        let options = GeneratorOptions {
+            default_declaration: "color: red; margin: 0".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            rule("p-4", &options),
+            (true, ".p-4 {\n  color: red;\n  margin: 0;\n}\n".to_string())
+        );
+    }
+
+    #[test]
+    fn empty_rules_are_skipped_unless_requested() {
+        let mut options = GeneratorOptions {
+            default_declaration: " ; ".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(rule("p-4", &options), (false, String::new()));
+
+        options.skip_empty = false;
+        assert_eq!(rule("p-4", &options), (false, ".p-4 {\n}\n".to_string()));
+    }
+
+    fn separated(rule_separator: RuleSeparator) -> String {
+        let options = GeneratorOptions {
+            default_declaration: "display: flex; gap: 1rem".to_string(),
+            rule_separator,
+            ..Default::default()
+        };
+        rule("p-4", &options).1 + &rule("m-1", &options).1
+    }
+
+    #[test]
+    fn double_separator_leaves_a_blank_line_between_rules() {
+        assert_eq!(
+            separated(RuleSeparator::Double),
+            ".p-4 {\n  display: flex;\n  gap: 1rem;\n}\n\n.m-1 {\n  display: flex;\n  gap: 1rem;\n}\n\n"
+        );
+    }
+
+    #[test]
+    fn single_separator_puts_rules_on_adjacent_lines() {
+        assert_eq!(
+            separated(RuleSeparator::Single),
+            ".p-4 {\n  display: flex;\n  gap: 1rem;\n}\n.m-1 {\n  display: flex;\n  gap: 1rem;\n}\n"
+        );
+    }
+
+    #[test]
+    fn none_separator_writes_one_rule_per_line() {
+        assert_eq!(
+            separated(RuleSeparator::None),
+            ".p-4 { display: flex; gap: 1rem; }\n.m-1 { display: flex; gap: 1rem; }\n"
+        );
+    }
+
+    fn temp_css(name: &str) -> (std::path::PathBuf, BufWriter<File>) {
+        let path =
+            std::env::temp_dir().join(format!("dx-style-{}-{}.css", name, std::process::id()));
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(&path)
+            .unwrap();
+        (path, BufWriter::new(file))
+    }
+
+    fn classes(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn banner_stays_once_at_top_across_rewrite_and_append() {
+        let (path, mut css_file) = temp_css("banner");
+        let options = GeneratorOptions {
+            banner: Some("/*! dx | MIT */".to_string()),
+            rule_separator: RuleSeparator::None,
+            ..Default::default()
+        };
+
+        write_css(&mut css_file, classes(&["m-1"]), true, &options).unwrap();
+        write_css(&mut css_file, classes(&["p-4"]), false, &options).unwrap();
+        write_css(&mut css_file, classes(&["flex"]), true, &options).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "/*! dx | MIT */\n.p-4 { display: flex; }\n.flex { display: flex; }\n"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn plain_banner_text_is_wrapped_in_a_comment() {
+        let (path, mut css_file) = temp_css("banner-plain");
+        let options = GeneratorOptions {
+            banner: Some("MIT License */ 2025".to_string()),
+            ..Default::default()
+        };
+
+        write_css(&mut css_file, Vec::new(), false, &options).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "/* MIT License * / 2025 */\n"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn classmap_maps_classes_to_themselves_with_json_escaping() {
+        let path = std::env::temp_dir()
+            .join(format!("dx-style-classmap-{}", std::process::id()))
+            .join("classmap.json");
+
+        write_classmap(
+            &path,
+            &classes(&["p-4", r#"w-[calc(100%-"a\b")]"#, "tab\there"]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            concat!(
+                "{\n",
+                "  \"p-4\": \"p-4\",\n",
+                "  \"w-[calc(100%-\\\"a\\\\b\\\")]\": \"w-[calc(100%-\\\"a\\\\b\\\")]\",\n",
+                "  \"tab\\u0009here\": \"tab\\u0009here\"\n",
+                "}\n"
+            )
+        );
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn empty_classmap_is_an_empty_object() {
+        let mut out = Vec::new();
+        write_json_string(&mut out, "").unwrap();
+        assert_eq!(out, b"\"\"");
+
+        let path = std::env::temp_dir().join(format!(
+            "dx-style-classmap-empty-{}.json",
+            std::process::id()
+        ));
+        write_classmap(&path, &[]).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn closure_resolver_output_survives_rewrite_and_append() {
+        let (path, mut css_file) = temp_css("resolver");
+        let options = GeneratorOptions {
+            resolver: Some(Box::new(|class: &str| match class {
+                "icon" => "background: url(data:image/png;base64,AAA=); width: 1rem".to_string(),
+                "sep" => "content: \";\"".to_string(),
+                _ => String::new(),
+            })),
+            rule_separator: RuleSeparator::None,
+            ..Default::default()
+        };
+
+        let unresolved = write_css(&mut css_file, classes(&["stale"]), false, &options).unwrap();
+        assert_eq!(unresolved, ["stale"]);
+        write_css(&mut css_file, classes(&["sep"]), true, &options).unwrap();
+
+        let unresolved =
+            write_css(&mut css_file, classes(&["icon", "nope"]), false, &options).unwrap();
+        assert_eq!(unresolved, ["nope"]);
+        let unresolved = write_css(&mut css_file, classes(&["sep"]), true, &options).unwrap();
+        assert!(unresolved.is_empty());
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            concat!(
+                ".icon { background: url(data:image/png;base64,AAA=); width: 1rem; }\n",
+                ".sep { content: \";\"; }\n"
+            )
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn banner_is_always_one_well_formed_comment() {
+        assert_eq!(banner_comment("/*! dx | MIT */"), "/*! dx | MIT */");
+        assert_eq!(banner_comment("  /* padded */\n\n"), "/* padded */");
+        assert_eq!(banner_comment("/* a */ b */"), "/* a * / b */");
+        assert_eq!(banner_comment("/*/"), "/* /* / */");
+        assert_eq!(banner_comment("MIT License"), "/* MIT License */");
+    }
 }
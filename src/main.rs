@@ -1,10 +1,11 @@
 use ahash::AHashSet;
 use colored::Colorize;
-use std::fs::{File, OpenOptions};
+use std::fs::{self, File, OpenOptions};
 use std::io::BufWriter;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
+mod cli;
 mod core;
 mod generator;
 mod parser;
@@ -13,15 +14,23 @@ mod watcher;
 
 use core::{rebuild_styles, AppState};
 
+fn ensure_file(path: &Path) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if !path.exists() {
+        File::create(path)?;
+    }
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let options = cli::parse_args(std::env::args().skip(1))?;
+
     println!("{}", "Starting DX Style core...".cyan());
 
-    if !Path::new("./playgrounds/html/style.css").exists() {
-        File::create("./playgrounds/html/style.css")?;
-    }
-    if !Path::new("./playgrounds/html/index.html").exists() {
-        File::create("./playgrounds/html/index.html")?;
-    }
+    ensure_file(Path::new("./playgrounds/html/style.css"))?;
+    ensure_file(Path::new("./playgrounds/html/index.html"))?;
 
     let css_file = OpenOptions::new()
         .write(true)
@@ -33,7 +42,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let app_state = Arc::new(Mutex::new(AppState {
         html_hash: 0,
         class_cache: AHashSet::default(),
+        unresolved_classes: AHashSet::default(),
         css_file: css_writer,
+        blocklist: options.blocklist,
+        traced_classes: options.trace.into_iter().collect(),
+        warn_output_bytes: options.warn_output_bytes,
+        classmap: options.classmap,
+        sort_order: options.sort_order,
+        generator_options: options.generator,
     }));
 
     rebuild_styles(app_state.clone(), true)?;
@@ -42,3 +58,24 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ensure_file_creates_missing_parent_directories() {
+        let root = std::env::temp_dir().join(format!("dx-style-ensure-{}", std::process::id()));
+        let path = root.join("dist/css/style.css");
+        let _ = fs::remove_dir_all(&root);
+
+        ensure_file(&path).unwrap();
+        assert!(path.is_file());
+
+        fs::write(&path, ".p-4 {}").unwrap();
+        ensure_file(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), ".p-4 {}");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
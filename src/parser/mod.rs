@@ -1,8 +1,29 @@
-use ahash::AHashSet;
+use ahash::{AHashMap, AHashSet};
 use memchr::{memchr, memmem::Finder};
 
 pub fn extract_classes_fast(html_bytes: &[u8], capacity_hint: usize) -> AHashSet<String> {
     let mut set = AHashSet::with_capacity(capacity_hint.max(64));
+    for_each_class(html_bytes, |cls| {
+        set.insert(cls.to_owned());
+    });
+    set
+}
+
+pub fn extract_classes_first_seen(
+    html_bytes: &[u8],
+    capacity_hint: usize,
+) -> AHashMap<String, usize> {
+    let mut first_seen = AHashMap::with_capacity(capacity_hint.max(64));
+    for_each_class(html_bytes, |cls| {
+        if !first_seen.contains_key(cls) {
+            let index = first_seen.len();
+            first_seen.insert(cls.to_owned(), index);
+        }
+    });
+    first_seen
+}
+
+fn for_each_class<'a>(html_bytes: &'a [u8], mut f: impl FnMut(&'a str)) {
     let finder = Finder::new(b"class");
     let mut pos = 0usize;
     let n = html_bytes.len();
@@ -39,12 +60,10 @@ pub fn extract_classes_fast(html_bytes: &[u8], capacity_hint: usize) -> AHashSet
         if let Ok(value_str) = std::str::from_utf8(&html_bytes[value_start..value_end]) {
             for cls in value_str.split_whitespace() {
                 if !cls.is_empty() {
-                    set.insert(cls.to_owned());
+                    f(cls);
                 }
             }
         }
         pos = value_end + 1;
     }
-
-    set
 }